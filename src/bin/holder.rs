@@ -5,95 +5,395 @@ mod types {
 use core::time;
 use std::thread;
 
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, Response};
 use ring::rand;
-use ring::signature::{Ed25519KeyPair, KeyPair, Signature};
-use types::Payload;
+use ring::signature::{self, Ed25519KeyPair, EcdsaKeyPair, KeyPair, RsaKeyPair};
+use types::{Header, JoseJson, Jwk};
 use uuid::Uuid;
 
 const FETCH_NONCE_URL: &str = "http://localhost:1843/nonce";
 const VERIFY_SIGNATURE_URL: &str = "http://localhost:1843/verify";
+const REGISTER_URL: &str = "http://localhost:1843/register";
+const REPLAY_NONCE_HEADER: &str = "Replay-Nonce";
+
+// A key pair the client can sign with. `ring` doesn't give Ed25519, ECDSA
+// and RSA key pairs a common signing trait, so this wraps the three we
+// support behind one interface keyed by the JWS `alg` they present as.
+enum SigningKey {
+    Ed25519(Ed25519KeyPair),
+    EcdsaP256(EcdsaKeyPair),
+    Rsa(RsaKeyPair),
+}
+
+impl SigningKey {
+    fn alg(&self) -> &'static str {
+        match self {
+            SigningKey::Ed25519(_) => types::alg::ED25519,
+            SigningKey::EcdsaP256(_) => types::alg::ECDSA_P256,
+            SigningKey::Rsa(_) => types::alg::RSA_PKCS1_SHA256,
+        }
+    }
+
+    fn jwk(&self) -> Result<Jwk, String> {
+        match self {
+            SigningKey::Ed25519(key_pair) => Ok(Jwk {
+                kty: "OKP".to_string(),
+                crv: Some("Ed25519".to_string()),
+                x: Some(types::b64url_encode(key_pair.public_key().as_ref())),
+                y: None,
+                n: None,
+                e: None,
+            }),
+            SigningKey::EcdsaP256(key_pair) => {
+                // Uncompressed SEC1 point: 0x04 || X || Y, 32 bytes each.
+                let point = key_pair.public_key().as_ref();
+                let (x, y) = point[1..].split_at(32);
+                Ok(Jwk {
+                    kty: "EC".to_string(),
+                    crv: Some("P-256".to_string()),
+                    x: Some(types::b64url_encode(x)),
+                    y: Some(types::b64url_encode(y)),
+                    n: None,
+                    e: None,
+                })
+            }
+            SigningKey::Rsa(key_pair) => {
+                let (n, e) = types::der_decode_rsa_public_key(key_pair.public_key().as_ref())?;
+                Ok(Jwk {
+                    kty: "RSA".to_string(),
+                    crv: None,
+                    x: None,
+                    y: None,
+                    n: Some(types::b64url_encode(&n)),
+                    e: Some(types::b64url_encode(&e)),
+                })
+            }
+        }
+    }
+
+    fn sign(&self, rng: &dyn rand::SecureRandom, message: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            SigningKey::Ed25519(key_pair) => Ok(key_pair.sign(message).as_ref().to_vec()),
+            SigningKey::EcdsaP256(key_pair) => key_pair
+                .sign(rng, message)
+                .map(|sig| sig.as_ref().to_vec())
+                .map_err(|_| "Failed to sign with ECDSA P-256 key".to_string()),
+            SigningKey::Rsa(key_pair) => {
+                let mut signature = vec![0u8; key_pair.public().modulus_len()];
+                key_pair
+                    .sign(&signature::RSA_PKCS1_SHA256, rng, message, &mut signature)
+                    .map_err(|_| "Failed to sign with RSA key".to_string())?;
+                Ok(signature)
+            }
+        }
+    }
+}
 
-fn generate_keypair() -> Result<Ed25519KeyPair, String> {
+// Generates a fresh key pair for `alg`. `ring` has no RSA key generation
+// support, so RSA callers need to bring their own PKCS#8 key via
+// `rsa_signing_key_from_pkcs8` instead.
+fn generate_keypair(alg: &str) -> Result<SigningKey, String> {
     let rng = rand::SystemRandom::new();
-    let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rng)
-        .map_err(|_| "Failed to generate Ed25519 Key Pair".to_string())?;
 
-    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref())
-        .map_err(|_| "Failed to parse Ed25519 Key Pair".to_string());
+    match alg {
+        types::alg::ED25519 => {
+            let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rng)
+                .map_err(|_| "Failed to generate Ed25519 Key Pair".to_string())?;
+            let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref())
+                .map_err(|_| "Failed to parse Ed25519 Key Pair".to_string())?;
+            Ok(SigningKey::Ed25519(key_pair))
+        }
+        types::alg::ECDSA_P256 => {
+            let pkcs8_bytes = EcdsaKeyPair::generate_pkcs8(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                &rng,
+            )
+            .map_err(|_| "Failed to generate ECDSA P-256 Key Pair".to_string())?;
+            let key_pair = EcdsaKeyPair::from_pkcs8(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                pkcs8_bytes.as_ref(),
+                &rng,
+            )
+            .map_err(|_| "Failed to parse ECDSA P-256 Key Pair".to_string())?;
+            Ok(SigningKey::EcdsaP256(key_pair))
+        }
+        types::alg::RSA_PKCS1_SHA256 => Err(
+            "RSA key generation isn't supported by ring; use rsa_signing_key_from_pkcs8 with an existing key".to_string(),
+        ),
+        other => Err(format!("Unsupported algorithm: {}", other)),
+    }
+}
 
-    key_pair
+// Builds a `SigningKey::Rsa` from an existing PKCS#8-encoded RSA private key,
+// for the one algorithm `generate_keypair` can't produce on its own.
+fn rsa_signing_key_from_pkcs8(pkcs8_bytes: &[u8]) -> Result<SigningKey, String> {
+    RsaKeyPair::from_pkcs8(pkcs8_bytes)
+        .map(SigningKey::Rsa)
+        .map_err(|_| "Failed to parse RSA Key Pair".to_string())
 }
 
-fn build_payload(
-    key_pair: Ed25519KeyPair,
+// A 2048-bit RSA private key, PKCS#8 DER-encoded, used only to exercise the
+// RSA-PKCS1-SHA256 path in `test_1` since `ring` can't generate one itself.
+// Not used for anything beyond this demo.
+#[rustfmt::skip]
+const RSA_TEST_KEY_PKCS8: &[u8] = &[
+    0x30, 0x82, 0x04, 0xbd, 0x02, 0x01, 0x00, 0x30, 0x0d, 0x06, 0x09, 0x2a,
+    0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05, 0x00, 0x04, 0x82,
+    0x04, 0xa7,
+    0x30, 0x82, 0x04, 0xa3, 0x02, 0x01, 0x00, 0x02, 0x82, 0x01, 0x01, 0x00,
+    0xcb, 0x21, 0x45, 0xd6, 0x84, 0xc3, 0x06, 0x5a, 0x19, 0x7c, 0xca, 0x1a,
+    0xbb, 0x98, 0xd8, 0x02, 0x43, 0xa1, 0x88, 0x02, 0xb2, 0xb7, 0x29, 0x49,
+    0xc6, 0x04, 0x9c, 0x66, 0x4e, 0x71, 0xe1, 0x9e, 0x0a, 0x15, 0x3d, 0x11,
+    0x05, 0xaf, 0xae, 0xd7, 0x6f, 0x92, 0x54, 0x3f, 0xff, 0x95, 0x1f, 0x40,
+    0xd0, 0x66, 0x6e, 0xff, 0xcd, 0x4e, 0x94, 0xeb, 0x27, 0x26, 0xcb, 0x66,
+    0xf0, 0x0a, 0x92, 0xbe, 0xed, 0x23, 0xec, 0xd5, 0x02, 0x67, 0xe9, 0x47,
+    0x9a, 0xc3, 0x04, 0x64, 0x1c, 0xcb, 0x8d, 0x48, 0xbb, 0x6f, 0x58, 0x86,
+    0x1d, 0xd3, 0xee, 0xcf, 0x72, 0x35, 0x28, 0x92, 0xa8, 0xa5, 0xca, 0x8e,
+    0x9f, 0xfe, 0x84, 0xb3, 0x63, 0x0d, 0x50, 0x35, 0xbf, 0xf0, 0x06, 0x94,
+    0x3b, 0x72, 0xa1, 0xc0, 0x20, 0xaa, 0xe3, 0xbb, 0x27, 0x02, 0x3b, 0x89,
+    0x71, 0xe8, 0x26, 0x0c, 0x73, 0xc6, 0x9f, 0xd7, 0x42, 0x80, 0x1f, 0xc7,
+    0x67, 0x4c, 0x86, 0xc4, 0x31, 0x50, 0xd5, 0x89, 0xc3, 0x9d, 0xc2, 0x0e,
+    0x9d, 0x85, 0xb3, 0xab, 0x47, 0x76, 0x5f, 0xc9, 0x16, 0x63, 0x36, 0x24,
+    0x89, 0x8c, 0x0d, 0x9a, 0x31, 0x08, 0x3d, 0x42, 0x66, 0xbc, 0xf2, 0xac,
+    0x71, 0x85, 0x6d, 0x0f, 0xad, 0x95, 0x33, 0x12, 0x8a, 0x9b, 0xf0, 0x47,
+    0xea, 0x17, 0xac, 0xb2, 0x83, 0xc1, 0x28, 0x9b, 0xb2, 0x2c, 0x80, 0x25,
+    0xb0, 0x9e, 0x98, 0x30, 0x82, 0x87, 0x41, 0x3b, 0xea, 0x2a, 0x8c, 0x29,
+    0x51, 0x47, 0x97, 0xc0, 0x6b, 0xad, 0xfd, 0x05, 0x36, 0x36, 0x94, 0xfc,
+    0x8e, 0xae, 0x36, 0x2d, 0xfd, 0x54, 0xb1, 0xd0, 0xc0, 0x96, 0x9f, 0x58,
+    0x85, 0x1a, 0x5f, 0x2f, 0x66, 0xee, 0x9d, 0xf6, 0x21, 0xef, 0xc2, 0xf1,
+    0x09, 0x10, 0xae, 0xf4, 0x60, 0xf7, 0x1a, 0x20, 0xac, 0x99, 0x5f, 0xe1,
+    0x6b, 0x55, 0x8b, 0x5d, 0x02, 0x03, 0x01, 0x00, 0x01, 0x02, 0x82, 0x01,
+    0x00, 0x09, 0x90, 0x1a, 0x1c, 0x41, 0xf3, 0xcc, 0x80, 0xc1, 0x62, 0x6b,
+    0xec, 0x06, 0x43, 0x93, 0xd2, 0x72, 0x21, 0xfb, 0x52, 0x8f, 0xdc, 0xaa,
+    0x47, 0x9c, 0x2d, 0x96, 0x0f, 0x5a, 0xf2, 0x11, 0x8f, 0xc1, 0xcf, 0x18,
+    0xd3, 0x8c, 0x53, 0xa6, 0x5a, 0x38, 0xc3, 0xc8, 0x23, 0x9b, 0x2c, 0xb2,
+    0x0e, 0xa3, 0x16, 0x5a, 0x1e, 0x16, 0x29, 0x9f, 0x93, 0xd2, 0x81, 0x5e,
+    0x62, 0x0c, 0xe5, 0x9d, 0x32, 0xfa, 0x87, 0x0e, 0x71, 0xee, 0x18, 0x66,
+    0x40, 0x70, 0xbb, 0x6d, 0xa1, 0x7e, 0x44, 0x36, 0xf5, 0x38, 0x48, 0x6d,
+    0xaa, 0x78, 0x0d, 0x04, 0xd9, 0x24, 0xa4, 0xa1, 0x5c, 0x5b, 0x84, 0x32,
+    0x9d, 0x09, 0xa5, 0xe4, 0x7e, 0xc2, 0xd3, 0x05, 0xa1, 0x02, 0x2a, 0xe7,
+    0xf9, 0xb7, 0xdd, 0x6b, 0x05, 0x58, 0x84, 0x5d, 0xb9, 0x99, 0xd9, 0xde,
+    0x63, 0xac, 0xc6, 0x79, 0x19, 0x6c, 0xf9, 0xea, 0x31, 0xbf, 0xf9, 0xe4,
+    0x28, 0xae, 0xa6, 0x90, 0x36, 0xde, 0x41, 0xd2, 0xc0, 0x7f, 0x08, 0xa8,
+    0xe1, 0xe7, 0x86, 0x5a, 0x80, 0x6b, 0x03, 0x55, 0x0f, 0x5b, 0x3f, 0xf8,
+    0xf3, 0xcc, 0x6f, 0x8c, 0x8d, 0xc1, 0x49, 0x70, 0x95, 0x23, 0x22, 0xd0,
+    0x02, 0xdd, 0xe7, 0xb6, 0x89, 0x72, 0x64, 0x06, 0x63, 0x04, 0xdf, 0xb3,
+    0xd9, 0xbe, 0x3c, 0x9a, 0x92, 0x01, 0x74, 0x74, 0x7e, 0xc1, 0x93, 0xc3,
+    0x34, 0x0c, 0x92, 0x4f, 0x59, 0xf4, 0x1a, 0xe2, 0x05, 0xae, 0x4d, 0xb9,
+    0x11, 0xbb, 0xbb, 0xa9, 0xe8, 0xf4, 0x99, 0xe5, 0x94, 0x3f, 0x59, 0x7e,
+    0xf7, 0x44, 0x7c, 0xe0, 0xe6, 0x1d, 0xf0, 0x81, 0x1f, 0x77, 0xc1, 0x91,
+    0x8d, 0xa4, 0x02, 0x50, 0x4c, 0x63, 0x5f, 0xc7, 0x5b, 0xa1, 0x3a, 0x03,
+    0x00, 0xa0, 0x24, 0xeb, 0xf0, 0x34, 0xa8, 0xb0, 0x32, 0x08, 0x37, 0x9b,
+    0x87, 0x56, 0x3f, 0x27, 0x01, 0x02, 0x81, 0x81, 0x00, 0xee, 0x23, 0xf4,
+    0x68, 0x32, 0xaf, 0x75, 0x8f, 0x33, 0x13, 0x13, 0xe2, 0xa6, 0x8f, 0xfc,
+    0x62, 0x37, 0xc9, 0xce, 0xeb, 0x59, 0xc3, 0x93, 0x8b, 0xe1, 0xd8, 0xba,
+    0x6b, 0x8a, 0xfa, 0xa6, 0x9d, 0xbc, 0x9b, 0xd0, 0x58, 0x88, 0x7c, 0x5f,
+    0x8a, 0x00, 0xf9, 0xc5, 0x6b, 0x3e, 0x66, 0x5d, 0xe8, 0x95, 0x2f, 0xf7,
+    0x1e, 0xdd, 0x3f, 0x6e, 0xf5, 0x53, 0x0c, 0x52, 0x4f, 0xee, 0xfc, 0xdc,
+    0x9d, 0xc7, 0xc8, 0xb2, 0x47, 0x1d, 0xce, 0xc7, 0x0d, 0xf1, 0x63, 0x2c,
+    0x90, 0xf8, 0xa0, 0x2a, 0x09, 0x1a, 0x4e, 0x07, 0xb2, 0x06, 0x6b, 0xe0,
+    0x3b, 0xf5, 0x87, 0x14, 0x42, 0x3c, 0x9a, 0x3f, 0xc0, 0xe7, 0xcc, 0xd6,
+    0x18, 0xd7, 0x16, 0x2b, 0x38, 0x50, 0xcd, 0x79, 0xc0, 0xdc, 0xc4, 0xd9,
+    0xcd, 0xdf, 0x04, 0xe3, 0xec, 0x52, 0x00, 0x18, 0xb5, 0x21, 0x17, 0x8e,
+    0x8a, 0xcc, 0x70, 0x70, 0xd5, 0x02, 0x81, 0x81, 0x00, 0xda, 0x5d, 0x27,
+    0x65, 0x9c, 0x3a, 0x09, 0x3b, 0xc2, 0x00, 0x29, 0x74, 0x13, 0x38, 0xec,
+    0xd8, 0x65, 0x0c, 0x7c, 0x6a, 0x81, 0xa1, 0xf9, 0xd1, 0x34, 0x27, 0x73,
+    0xaa, 0x62, 0xf6, 0xe1, 0x20, 0x09, 0x2d, 0xa7, 0x39, 0x36, 0xc2, 0xdb,
+    0xae, 0x01, 0xeb, 0xfa, 0x4c, 0x4b, 0x8c, 0x5c, 0xa7, 0xce, 0xb2, 0x46,
+    0x42, 0xc3, 0xbe, 0x82, 0x00, 0xc8, 0x9e, 0xa8, 0xe4, 0x5f, 0x30, 0x20,
+    0x8f, 0x3a, 0xd6, 0xad, 0xcc, 0xc8, 0xfe, 0x54, 0x84, 0xad, 0xee, 0x5e,
+    0x17, 0x65, 0x0a, 0x15, 0xef, 0x33, 0x0b, 0xeb, 0x4d, 0x06, 0x61, 0xbf,
+    0x16, 0x9a, 0x94, 0x35, 0xce, 0xa4, 0x85, 0x6b, 0xa5, 0xca, 0x19, 0x54,
+    0xee, 0x3c, 0x83, 0x4a, 0xdc, 0xe4, 0xe2, 0x84, 0xd6, 0x85, 0x87, 0x7d,
+    0x62, 0xa0, 0x67, 0x47, 0x2d, 0x2e, 0x4f, 0x54, 0x2a, 0xe6, 0xf9, 0x35,
+    0xa8, 0x57, 0x1c, 0x34, 0x69, 0x02, 0x81, 0x80, 0x0a, 0x0c, 0x10, 0x4a,
+    0xb9, 0x20, 0xb7, 0xf8, 0x03, 0xb2, 0xa1, 0x5b, 0x25, 0xa5, 0xdb, 0x83,
+    0x15, 0x51, 0x79, 0x5e, 0x6a, 0x9a, 0x1a, 0xc6, 0x83, 0x0f, 0xce, 0xc5,
+    0xac, 0xc6, 0x68, 0x73, 0x2d, 0x3a, 0xf1, 0xae, 0x5a, 0x8b, 0xcc, 0xc8,
+    0x18, 0xc4, 0x70, 0xf4, 0xd6, 0x5c, 0x21, 0x48, 0x27, 0xcb, 0x2f, 0x44,
+    0xe9, 0x16, 0xbd, 0x0a, 0xb2, 0xa7, 0x49, 0x6d, 0xbf, 0xea, 0x81, 0x30,
+    0x52, 0xf6, 0x7f, 0xb7, 0x0a, 0x65, 0xe7, 0xf6, 0x09, 0xa2, 0x22, 0x86,
+    0x37, 0x47, 0x22, 0x9e, 0xe1, 0xb2, 0x3b, 0x16, 0xe2, 0xb8, 0x78, 0x6c,
+    0x6f, 0x2e, 0x74, 0xc7, 0x5a, 0xa1, 0x33, 0x61, 0x63, 0x26, 0x3c, 0x3e,
+    0x2e, 0x0c, 0xec, 0x63, 0xe2, 0xdc, 0xd8, 0x6f, 0x2f, 0xa1, 0x4a, 0x83,
+    0x2a, 0x0e, 0x9d, 0xc3, 0x21, 0xae, 0x89, 0x5c, 0x52, 0xfa, 0x18, 0x74,
+    0x78, 0xad, 0xa1, 0xc5, 0x02, 0x81, 0x80, 0x51, 0x27, 0xcf, 0xed, 0x96,
+    0xbb, 0x87, 0x14, 0xfb, 0x8c, 0x1d, 0xdb, 0xf3, 0x0c, 0xf4, 0x11, 0x94,
+    0xb4, 0xa8, 0x44, 0xd9, 0x3a, 0xfe, 0xe2, 0xbe, 0xb4, 0x6e, 0xeb, 0x83,
+    0x6e, 0x67, 0xcb, 0x05, 0x3e, 0x3f, 0x69, 0x27, 0x2e, 0x12, 0x81, 0x40,
+    0x5b, 0x98, 0xa1, 0xe4, 0x6e, 0x1d, 0x3d, 0x8c, 0xb7, 0x7b, 0x1f, 0x73,
+    0x2a, 0x89, 0x9c, 0x8a, 0xf3, 0x54, 0xa7, 0xd3, 0xac, 0xca, 0x5d, 0x11,
+    0x2c, 0x1a, 0x5a, 0x02, 0xd5, 0x6b, 0x4a, 0x08, 0x6e, 0x8f, 0xef, 0xb7,
+    0xf0, 0xa0, 0xa8, 0x4e, 0xdb, 0x7e, 0x2f, 0x19, 0x51, 0x18, 0xb8, 0xe8,
+    0xe8, 0x6e, 0x7b, 0x07, 0x70, 0x42, 0xca, 0xd2, 0x2a, 0xca, 0x02, 0xe4,
+    0xe0, 0x92, 0xe1, 0x37, 0xfb, 0xec, 0x0c, 0xe2, 0x81, 0xb1, 0x9b, 0x07,
+    0x2c, 0x7b, 0x2a, 0x92, 0x8c, 0x8f, 0x26, 0x86, 0xc3, 0x56, 0x2a, 0xe3,
+    0x7d, 0x2b, 0x09, 0x02, 0x81, 0x81, 0x00, 0x8d, 0xd5, 0xd5, 0x2d, 0xbc,
+    0xf3, 0xfd, 0xb6, 0x6f, 0xe2, 0x22, 0x76, 0x99, 0x75, 0xef, 0x90, 0xc9,
+    0x38, 0x67, 0x7d, 0xcf, 0xbc, 0x68, 0x45, 0x70, 0x46, 0x73, 0x4a, 0xf8,
+    0xe7, 0x92, 0xaa, 0x0e, 0x4f, 0x80, 0x17, 0x5f, 0xf9, 0xb6, 0x8f, 0x7f,
+    0x91, 0x67, 0xcf, 0x4b, 0x7d, 0x3d, 0x2f, 0xb9, 0x72, 0xf0, 0x4d, 0x14,
+    0xc5, 0x6a, 0x34, 0xaa, 0x12, 0x82, 0x64, 0x21, 0x68, 0x35, 0x30, 0x57,
+    0x7f, 0x24, 0x29, 0xf9, 0x90, 0x80, 0x27, 0xc2, 0xdc, 0x46, 0x7d, 0x11,
+    0xe5, 0xcb, 0x61, 0x24, 0x92, 0x34, 0xac, 0x83, 0xb0, 0x4b, 0xfc, 0x29,
+    0x5f, 0x69, 0x9b, 0x80, 0x4a, 0x7e, 0xb8, 0x7f, 0x7b, 0xc2, 0xef, 0xb1,
+    0x2d, 0x5b, 0x3c, 0x39, 0x2c, 0xe9, 0x34, 0xe6, 0x6f, 0x6f, 0xaf, 0xb2,
+    0xba, 0xdd, 0xb2, 0xf3, 0x69, 0x02, 0x83, 0xff, 0x1e, 0x00, 0xcf, 0x11,
+    0x65, 0x80, 0x99,
+];
+
+// Builds a JWS-style envelope for `message`, signed over
+// `ASCII(base64url(protected) || "." || base64url(signed_bytes))`.
+// `signed_bytes` is normally just `message`, but tests pass a different
+// value to simulate a signature that doesn't match the declared payload.
+// When `kid` is `Some`, the protected header references the registered
+// account instead of embedding the raw public key.
+fn build_jose(
+    key: &SigningKey,
+    kid: Option<String>,
     message: &[u8],
-    sig: Signature,
+    signed_bytes: &[u8],
     nonce: String,
-) -> Payload {
-    let public_key_bytes = key_pair.public_key().as_ref();
-
-    Payload {
-        nonce: nonce.to_string(),
-        message: message.as_ref().to_vec(),
-        signature: sig.as_ref().to_vec(),
-        public_key: public_key_bytes.to_vec(),
-    }
+) -> Result<JoseJson, String> {
+    let header = Header {
+        alg: key.alg().to_string(),
+        typ: types::SIG_TYP_V1.to_string(),
+        nonce,
+        jwk: if kid.is_none() { Some(key.jwk()?) } else { None },
+        kid,
+    };
+    let protected = types::b64url_encode(&serde_json::to_vec(&header).unwrap());
+    let payload = types::b64url_encode(message);
+    let signed_payload = types::b64url_encode(signed_bytes);
+
+    let rng = rand::SystemRandom::new();
+    let signature = key.sign(&rng, &types::signing_input(&protected, &signed_payload))?;
+
+    Ok(JoseJson {
+        protected,
+        payload,
+        signature: types::b64url_encode(&signature),
+    })
 }
 
-// HTTP Related Functions
-// Fetch a nonce value from the server
-fn fetch_nonce() -> Result<String, String> {
-    println!("  1. Fetching Nonce...");
-    let client = Client::new();
-    let response = client
-        .get(FETCH_NONCE_URL)
-        .send()
-        .map_err(|_| "Failed to fetch nonce".to_string())?;
+// Pulls the `Replay-Nonce` header a handler attached to its response.
+fn read_replay_nonce(response: &Response) -> Result<String, String> {
     let nonce = response
-        .text()
-        .map_err(|_| "Failed to fetch nonce".to_string())?;
+        .headers()
+        .get(REPLAY_NONCE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| "Missing Replay-Nonce header".to_string())?
+        .to_string();
 
-    // Check if is a valid uuid
     Uuid::parse_str(&nonce).map_err(|_| "Invalid nonce".to_string())?;
 
-    //Ok("bdf3b304-0e37-4639-9eed-039e16f1c171".to_string())
-
     Ok(nonce)
 }
 
-// Assks for the Verifier to verify the signature of the payload
-fn verify_signature(payload: Payload) -> Result<(), String> {
-    let client = Client::new();
+// Talks to the verifier, keeping the most recently issued nonce around so
+// callers can chain signed requests without a dedicated `/nonce` round-trip
+// in between.
+struct HolderSession {
+    client: Client,
+    cached_nonce: Option<String>,
+}
 
-    let payload_json =
-        serde_json::to_string(&payload).map_err(|_| "Failed to serialize payload".to_string())?;
+impl HolderSession {
+    fn new() -> Self {
+        Self {
+            client: Client::new(),
+            cached_nonce: None,
+        }
+    }
 
-    let response = client
-        .post(VERIFY_SIGNATURE_URL)
-        .header("Content-Type", "application/json")
-        .body(payload_json)
-        .send()
-        .map_err(|_| "Failed to fetch nonce".to_string())?;
+    // Fetch a nonce value from the server
+    fn fetch_nonce(&mut self) -> Result<String, String> {
+        println!("  1. Fetching Nonce...");
+        let response = self
+            .client
+            .get(FETCH_NONCE_URL)
+            .send()
+            .map_err(|_| "Failed to fetch nonce".to_string())?;
+        let nonce = read_replay_nonce(&response)?;
+        self.cached_nonce = Some(nonce.clone());
+        Ok(nonce)
+    }
 
-    if response.status().is_success() {
-        Ok(())
-    } else if response.status() == 401 {
-        let text_response = response.text().unwrap();
-        Err(text_response)
-    } else {
-        Err("Failed to verify signature".to_string())
+    // Returns the nonce cached from the previous response, fetching a fresh
+    // one from `/nonce` only if none is available yet.
+    fn next_nonce(&mut self) -> Result<String, String> {
+        match self.cached_nonce.take() {
+            Some(nonce) => Ok(nonce),
+            None => self.fetch_nonce(),
+        }
+    }
+
+    // Registers a public key with the verifier, returning the account
+    // identifier (the JWK thumbprint) it can be referenced by afterwards.
+    fn register(&mut self, jwk: &Jwk) -> Result<String, String> {
+        let jwk_json =
+            serde_json::to_string(jwk).map_err(|_| "Failed to serialize JWK".to_string())?;
+
+        let response = self
+            .client
+            .post(REGISTER_URL)
+            .header("Content-Type", "application/json")
+            .body(jwk_json)
+            .send()
+            .map_err(|_| "Failed to register account".to_string())?;
+
+        if let Ok(nonce) = read_replay_nonce(&response) {
+            self.cached_nonce = Some(nonce);
+        }
+
+        if response.status().is_success() {
+            response
+                .text()
+                .map_err(|_| "Failed to read account id".to_string())
+        } else {
+            Err(response
+                .text()
+                .unwrap_or_else(|_| "Failed to register account".to_string()))
+        }
+    }
+
+    // Asks the Verifier to verify the signature of the JWS envelope
+    fn verify_signature(&mut self, jose: JoseJson) -> Result<(), String> {
+        let jose_json =
+            serde_json::to_string(&jose).map_err(|_| "Failed to serialize payload".to_string())?;
+
+        let response = self
+            .client
+            .post(VERIFY_SIGNATURE_URL)
+            .header("Content-Type", "application/json")
+            .body(jose_json)
+            .send()
+            .map_err(|_| "Failed to send signature for verification".to_string())?;
+
+        if let Ok(nonce) = read_replay_nonce(&response) {
+            self.cached_nonce = Some(nonce);
+        }
+
+        if response.status().is_success() {
+            Ok(())
+        } else if response.status() == 401 {
+            let text_response = response.text().unwrap();
+            Err(text_response)
+        } else {
+            Err("Failed to verify signature".to_string())
+        }
     }
 }
 
 fn main() {
     const MESSAGE: &[u8] = b"Hello, world!";
 
-    // Here we gonna set up 4 cases:
-    //   1. Valid Signature
+    // Here we gonna set up 5 cases:
+    //   1. Valid Signature (exercised for every supported algorithm)
     //   2. Invalid Signature
     //   3. Expired Nonce
     //   4. Invalid Nonce
+    //   5. Valid Check using a registered account (`kid`)
 
     println!();
     test_1(MESSAGE);
@@ -104,35 +404,63 @@ fn main() {
     println!();
     test_4(MESSAGE);
     println!();
+    test_5(MESSAGE);
+    println!();
 }
 
 // TEST FUNCTIONS
 fn test_1(message: &[u8]) {
-    // Generate Key Pair
-    let key_pair = match generate_keypair() {
-        Ok(key_pair) => key_pair,
+    // `generate_keypair` can't produce an RSA key since `ring` doesn't
+    // support RSA key generation, so that case uses a fixed PKCS#8 key
+    // via `rsa_signing_key_from_pkcs8` instead of generating one.
+    const ALGORITHMS: [&str; 2] = [types::alg::ED25519, types::alg::ECDSA_P256];
+
+    for alg in ALGORITHMS {
+        let key = match generate_keypair(alg) {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+
+        // Case 1: Valid Check
+        println!("---- Case 1: Valid Check ({}) ----", alg);
+        let mut session = HolderSession::new();
+        let nonce = match session.next_nonce() {
+            Ok(nonce) => nonce,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+        };
+        run_valid_check(&mut session, &key, message, nonce, None);
+    }
+
+    let rsa_key = match rsa_signing_key_from_pkcs8(RSA_TEST_KEY_PKCS8) {
+        Ok(key) => key,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
         }
     };
 
-    // Case 1: Valid Check
-    println!("---- Case 1: Valid Check ----");
-    let nonce = match fetch_nonce() {
+    println!("---- Case 1: Valid Check ({}) ----", types::alg::RSA_PKCS1_SHA256);
+    let mut session = HolderSession::new();
+    let nonce = match session.next_nonce() {
         Ok(nonce) => nonce,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
         }
     };
-    run_valid_check(key_pair, message, nonce, None);
+    run_valid_check(&mut session, &rsa_key, message, nonce, None);
 }
 
 fn test_2(message: &[u8]) {
     // Generate Key Pair
-    let key_pair = match generate_keypair() {
-        Ok(key_pair) => key_pair,
+    let key = match generate_keypair(types::alg::ED25519) {
+        Ok(key) => key,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
@@ -140,29 +468,30 @@ fn test_2(message: &[u8]) {
     };
 
     println!("\n---- Case 2: Valid Check followed by Check with same nonce (Should Fail) ----");
-    let nonce = match fetch_nonce() {
+    let mut session = HolderSession::new();
+    let nonce = match session.next_nonce() {
         Ok(nonce) => nonce,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
         }
     };
-    run_valid_check(key_pair, message, nonce.clone(), None);
+    run_valid_check(&mut session, &key, message, nonce.clone(), None);
 
-    let key_pair2 = match generate_keypair() {
-        Ok(key_pair) => key_pair,
+    let key2 = match generate_keypair(types::alg::ED25519) {
+        Ok(key) => key,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
         }
     };
-    run_valid_check(key_pair2, message, nonce.clone(), None);
+    run_valid_check(&mut session, &key2, message, nonce.clone(), None);
 }
 
 fn test_3(message: &[u8]) {
     // Generate Key Pair
-    let key_pair = match generate_keypair() {
-        Ok(key_pair) => key_pair,
+    let key = match generate_keypair(types::alg::ED25519) {
+        Ok(key) => key,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
@@ -170,7 +499,8 @@ fn test_3(message: &[u8]) {
     };
 
     println!("---- Case 3: Valid Nonce but Invalid Signature (Should Fail) ----");
-    let nonce = match fetch_nonce() {
+    let mut session = HolderSession::new();
+    let nonce = match session.next_nonce() {
         Ok(nonce) => nonce,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -178,13 +508,13 @@ fn test_3(message: &[u8]) {
         }
     };
 
-    run_valid_check(key_pair, message, nonce, Some(b"RANDOM MESSAGE"));
+    run_valid_check(&mut session, &key, message, nonce, Some(b"RANDOM MESSAGE"));
 }
 
 fn test_4(message: &[u8]) {
     // Generate Key Pair
-    let key_pair = match generate_keypair() {
-        Ok(key_pair) => key_pair,
+    let key = match generate_keypair(types::alg::ED25519) {
+        Ok(key) => key,
         Err(e) => {
             eprintln!("Error: {}", e);
             return;
@@ -192,7 +522,8 @@ fn test_4(message: &[u8]) {
     };
 
     println!("\n---- Case 4: Valid Check After Nonce Expiration (Should Fail) ----");
-    let nonce = match fetch_nonce() {
+    let mut session = HolderSession::new();
+    let nonce = match session.next_nonce() {
         Ok(nonce) => nonce,
         Err(e) => {
             eprintln!("Error: {}", e);
@@ -200,11 +531,62 @@ fn test_4(message: &[u8]) {
         }
     };
     thread::sleep(time::Duration::from_secs(6));
-    run_valid_check(key_pair, message, nonce, None);
+    run_valid_check(&mut session, &key, message, nonce, None);
+}
+
+fn test_5(message: &[u8]) {
+    // Generate Key Pair
+    let key = match generate_keypair(types::alg::ED25519) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    println!("\n---- Case 5: Valid Check Using a Registered Account (kid) ----");
+    let mut session = HolderSession::new();
+
+    println!("  0. Registering Account...");
+    let jwk = match key.jwk() {
+        Ok(jwk) => jwk,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    let kid = match session.register(&jwk) {
+        Ok(kid) => kid,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+
+    let nonce = match session.next_nonce() {
+        Ok(nonce) => nonce,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    run_check(&mut session, &key, Some(kid), message, nonce, None);
 }
 
 fn run_valid_check(
-    key_pair: Ed25519KeyPair,
+    session: &mut HolderSession,
+    key: &SigningKey,
+    message: &[u8],
+    nonce: String,
+    wrong_message: Option<&[u8]>,
+) {
+    run_check(session, key, None, message, nonce, wrong_message);
+}
+
+fn run_check(
+    session: &mut HolderSession,
+    key: &SigningKey,
+    kid: Option<String>,
     message: &[u8],
     nonce: String,
     wrong_message: Option<&[u8]>,
@@ -212,12 +594,17 @@ fn run_valid_check(
     let message_to_sign = wrong_message.unwrap_or(message);
 
     println!("  2. Signing Message Correctly...");
-    let sig = key_pair.sign(message_to_sign);
     println!("  3. Building Payload...");
-    let payload = build_payload(key_pair, message, sig, nonce);
+    let jose = match build_jose(key, kid, message, message_to_sign, nonce) {
+        Ok(jose) => jose,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
 
     println!("  4. Verifying Signature...");
-    match verify_signature(payload) {
+    match session.verify_signature(jose) {
         Ok(_) => println!("Signature verified successfully!"),
         Err(e) => eprintln!("Error: {}", e),
     };
@@ -2,12 +2,15 @@ mod types {
     include!("../types.rs");
 }
 
+use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Status;
 use rocket::response::status;
-use types::Payload;
+use rocket::{Request, Response};
+use types::{Header, JoseJson, Jwk};
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 use uuid::Uuid;
 
@@ -16,6 +19,8 @@ use ring::signature;
 #[macro_use]
 extern crate rocket;
 
+const REPLAY_NONCE_HEADER: &str = "Replay-Nonce";
+
 struct NonceEntry {
     created_at: Instant,
     used: bool,
@@ -24,14 +29,31 @@ struct NonceEntry {
 struct NonceStore {
     nonces: HashMap<String, NonceEntry>, // nonce -> NonceEntry
     expiration_time: Duration,
+    max_capacity: Option<usize>,
 }
 
 impl NonceStore {
-    fn new(expiration_seconds: u64) -> Self {
-        Self {
+    // Builds the store and spawns a background thread that periodically
+    // sweeps expired/used nonces and enforces `max_capacity`, so the map
+    // doesn't grow unbounded under long-running load.
+    fn new(
+        expiration_seconds: u64,
+        sweep_interval_seconds: u64,
+        max_capacity: Option<usize>,
+    ) -> Arc<Mutex<Self>> {
+        let store = Arc::new(Mutex::new(Self {
             nonces: HashMap::new(),
             expiration_time: Duration::from_secs(expiration_seconds),
-        }
+            max_capacity,
+        }));
+
+        let sweeper_store = Arc::clone(&store);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(sweep_interval_seconds));
+            sweeper_store.lock().unwrap().sweep();
+        });
+
+        store
     }
 
     fn generate_nonce(&mut self) -> String {
@@ -66,47 +88,231 @@ impl NonceStore {
         }
         false
     }
+
+    // Drops expired and already-used entries, then evicts the oldest
+    // remaining ones until `max_capacity` is satisfied (if set).
+    fn sweep(&mut self) {
+        let expiration_time = self.expiration_time;
+        self.nonces
+            .retain(|_, entry| !entry.used && entry.created_at.elapsed() <= expiration_time);
+
+        let Some(max_capacity) = self.max_capacity else {
+            return;
+        };
+
+        while self.nonces.len() > max_capacity {
+            let oldest = self
+                .nonces
+                .iter()
+                .min_by_key(|(_, entry)| entry.created_at)
+                .map(|(nonce, _)| nonce.clone());
+
+            match oldest {
+                Some(nonce) => {
+                    self.nonces.remove(&nonce);
+                }
+                None => break,
+            }
+        }
+    }
 }
 
 type NonceStoreRef = rocket::State<Arc<Mutex<NonceStore>>>;
 
-// Generate nonce
+// A registered account: the public key it was registered with, and the
+// algorithm that key is used under.
+struct Account {
+    jwk: Jwk,
+    alg: String,
+}
+
+// Accounts keyed by JWK thumbprint (RFC 7638), mirroring `NonceStore`. This
+// lets a holder register its key once via `/register` and then sign
+// requests by `kid` instead of resending the raw key every time.
+struct AccountStore {
+    accounts: HashMap<String, Account>, // thumbprint -> Account
+}
+
+impl AccountStore {
+    fn new() -> Self {
+        Self {
+            accounts: HashMap::new(),
+        }
+    }
+
+    fn register(&mut self, jwk: Jwk, alg: String) -> Result<String, String> {
+        let kid = types::jwk_thumbprint(&jwk)?;
+        self.accounts.insert(kid.clone(), Account { jwk, alg });
+        Ok(kid)
+    }
+
+    fn get(&self, kid: &str) -> Option<&Account> {
+        self.accounts.get(kid)
+    }
+}
+
+type AccountStoreRef = rocket::State<Arc<Mutex<AccountStore>>>;
+
+// Mints a fresh nonce and attaches it to every response as a `Replay-Nonce`
+// header, ACME-style, so a client can chain signed requests without a
+// separate round-trip to `/nonce` in between.
+struct ReplayNonceFairing;
+
+#[rocket::async_trait]
+impl Fairing for ReplayNonceFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Replay-Nonce Header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Some(store) = request.rocket().state::<Arc<Mutex<NonceStore>>>() {
+            let nonce = store.lock().unwrap().generate_nonce();
+            response.set_raw_header(REPLAY_NONCE_HEADER, nonce);
+        }
+    }
+}
+
+// Generate nonce. The actual value is attached by `ReplayNonceFairing`, so
+// this handler only needs to produce a response for the fairing to decorate.
 #[get("/nonce")]
-fn nonce(store: &NonceStoreRef) -> String {
-    let mut store = store.lock().unwrap();
+fn nonce() -> Status {
+    Status::Ok
+}
+
+// Registers a public key and returns its JWK thumbprint as a stable account
+// identifier (`kid`), which `/verify` callers can then present instead of
+// resending the raw key on every request.
+#[post("/register", format = "json", data = "<jwk>")]
+fn register(jwk: String, accounts: &AccountStoreRef) -> status::Custom<String> {
+    let jwk: Jwk = match serde_json::from_str(&jwk) {
+        Ok(jwk) => jwk,
+        Err(e) => return status::Custom(Status::BadRequest, format!("Failed to parse JWK: {}", e)),
+    };
+    let alg = match types::alg_for_jwk(&jwk) {
+        Ok(alg) => alg,
+        Err(e) => return status::Custom(Status::BadRequest, e),
+    };
 
-    store.generate_nonce()
+    match accounts.lock().unwrap().register(jwk, alg.to_string()) {
+        Ok(kid) => status::Custom(Status::Ok, kid),
+        Err(e) => status::Custom(Status::BadRequest, e),
+    }
 }
 
-#[post("/verify", format = "json", data = "<payload>")]
-fn verify_signature(payload: String, store: &NonceStoreRef) -> status::Custom<String> {
+#[post("/verify", format = "json", data = "<jose>")]
+fn verify_signature(
+    jose: String,
+    store: &NonceStoreRef,
+    accounts: &AccountStoreRef,
+) -> status::Custom<String> {
     let mut store = store.lock().unwrap();
 
-    let payload: Payload = serde_json::from_str(&payload)
-        .map_err(|e| {
-            status::Custom(
+    let jose: JoseJson = match serde_json::from_str(&jose) {
+        Ok(jose) => jose,
+        Err(e) => {
+            return status::Custom(
                 Status::BadRequest,
                 format!("Failed to parse payload: {}", e),
             )
-        })
-        .unwrap();
+        }
+    };
+
+    let header_bytes = match types::b64url_decode(&jose.protected) {
+        Ok(bytes) => bytes,
+        Err(e) => return status::Custom(Status::BadRequest, e),
+    };
+    let header: Header = match serde_json::from_slice(&header_bytes) {
+        Ok(header) => header,
+        Err(e) => {
+            return status::Custom(
+                Status::BadRequest,
+                format!("Failed to parse protected header: {}", e),
+            )
+        }
+    };
 
-    // Extract the payload parts
-    let message_bytes = payload.message.as_slice();
-    let public_key_bytes = payload.public_key.as_slice();
-    let signature_bytes = payload.signature.as_slice();
-    let nonce = payload.nonce.as_str();
+    if header.typ != types::SIG_TYP_V1 {
+        return status::Custom(
+            Status::BadRequest,
+            format!("Unsupported protected header type: {}", header.typ),
+        );
+    }
+
+    let signature_bytes = match types::b64url_decode(&jose.signature) {
+        Ok(bytes) => bytes,
+        Err(e) => return status::Custom(Status::BadRequest, e),
+    };
 
-    if !store.verify_and_use_nonce(nonce) {
+    // Resolve which JWK/alg this request actually signed with: either the
+    // inline key, or the one a `kid` points to. If both are present, the
+    // inline key must match the registered account's key.
+    let (jwk, alg) = match (&header.jwk, &header.kid) {
+        (_, Some(kid)) => {
+            let accounts = accounts.lock().unwrap();
+            let account = match accounts.get(kid) {
+                Some(account) => account,
+                None => {
+                    return status::Custom(Status::Unauthorized, "Unknown account".to_string())
+                }
+            };
+            if let Some(inline_jwk) = &header.jwk {
+                match types::jwk_thumbprint(inline_jwk) {
+                    Ok(thumbprint) if &thumbprint == kid => {}
+                    _ => {
+                        return status::Custom(
+                            Status::Unauthorized,
+                            "Signing key does not match registered account".to_string(),
+                        )
+                    }
+                }
+            }
+            (account.jwk.clone(), account.alg.clone())
+        }
+        (Some(jwk), None) => (jwk.clone(), header.alg.clone()),
+        (None, None) => {
+            return status::Custom(
+                Status::BadRequest,
+                "Protected header must include either \"jwk\" or \"kid\"".to_string(),
+            )
+        }
+    };
+
+    let verification_algorithm = match types::verification_algorithm(&alg) {
+        Some(alg) => alg,
+        None => return status::Custom(Status::BadRequest, format!("Unsupported algorithm: {}", alg)),
+    };
+    // The declared `alg` has to be the one the resolved key is used under.
+    match types::alg_for_jwk(&jwk) {
+        Ok(key_alg) if key_alg == header.alg => {}
+        _ => {
+            return status::Custom(
+                Status::BadRequest,
+                format!("Algorithm {} does not match the signing key", header.alg),
+            )
+        }
+    }
+    let public_key_bytes = match types::jwk_public_key_bytes(&alg, &jwk) {
+        Ok(bytes) => bytes,
+        Err(e) => return status::Custom(Status::BadRequest, e),
+    };
+
+    // This both confirms the nonce in the protected header is one we
+    // actually issued (unused and unexpired) and consumes it. That header is
+    // part of the signing input below, so the signature is tied to it.
+    if !store.verify_and_use_nonce(&header.nonce) {
         return status::Custom(Status::Unauthorized, "Invalid or expired nonce".to_string());
     }
 
+    let signing_input = types::signing_input(&jose.protected, &jose.payload);
     let holder_public_key =
-        signature::UnparsedPublicKey::new(&signature::ED25519, public_key_bytes);
+        signature::UnparsedPublicKey::new(verification_algorithm, &public_key_bytes);
 
     // Verify the signature
     if holder_public_key
-        .verify(message_bytes, signature_bytes)
+        .verify(&signing_input, &signature_bytes)
         .is_ok()
     {
         return status::Custom(Status::Ok, "Signature verified successfully".to_string());
@@ -118,13 +324,129 @@ fn verify_signature(payload: String, store: &NonceStoreRef) -> status::Custom<St
 #[launch]
 fn rocket() -> _ {
     // 5 seconds expiration time - just to make it easier to test
-    let nonce_store = NonceStore::new(5);
+    let nonce_store = NonceStore::new(5, 2, Some(10_000));
 
     rocket::build()
         .configure(rocket::Config {
             port: 1843,
             ..Default::default()
         })
-        .manage(Arc::new(Mutex::new(nonce_store)))
-        .mount("/", routes![nonce, verify_signature])
+        .manage(nonce_store)
+        .manage(Arc::new(Mutex::new(AccountStore::new())))
+        .attach(ReplayNonceFairing)
+        .mount("/", routes![nonce, verify_signature, register])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(expiration_time: Duration, max_capacity: Option<usize>) -> NonceStore {
+        NonceStore {
+            nonces: HashMap::new(),
+            expiration_time,
+            max_capacity,
+        }
+    }
+
+    #[test]
+    fn sweep_removes_expired_and_used_nonces() {
+        let mut store = store(Duration::from_millis(50), None);
+
+        for _ in 0..5 {
+            store.generate_nonce();
+        }
+        let used_nonce = store.nonces.keys().next().unwrap().clone();
+        store.verify_and_use_nonce(&used_nonce);
+        assert_eq!(store.nonces.len(), 5);
+
+        thread::sleep(Duration::from_millis(100));
+        store.sweep();
+
+        assert!(store.nonces.is_empty());
+    }
+
+    #[test]
+    fn sweep_evicts_oldest_nonces_beyond_capacity() {
+        let mut store = store(Duration::from_secs(60), Some(3));
+
+        let mut generated = Vec::new();
+        for _ in 0..5 {
+            generated.push(store.generate_nonce());
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(store.nonces.len(), 5);
+
+        store.sweep();
+
+        assert_eq!(store.nonces.len(), 3);
+        for nonce in &generated[2..] {
+            assert!(store.nonces.contains_key(nonce));
+        }
+    }
+
+    fn ed25519_jwk(x: &str) -> Jwk {
+        Jwk {
+            kty: "OKP".to_string(),
+            crv: Some("Ed25519".to_string()),
+            x: Some(x.to_string()),
+            y: None,
+            n: None,
+            e: None,
+        }
+    }
+
+    #[test]
+    fn jwk_thumbprint_is_deterministic() {
+        let jwk = ed25519_jwk("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo");
+
+        assert_eq!(
+            types::jwk_thumbprint(&jwk).unwrap(),
+            types::jwk_thumbprint(&jwk).unwrap()
+        );
+    }
+
+    #[test]
+    fn jwk_thumbprint_differs_for_different_keys() {
+        let a = ed25519_jwk("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo");
+        let b = ed25519_jwk("MkTlOhuPqLmzWUicNWDJqvC7AYoyJLKdXSULXizquOM");
+
+        assert_ne!(
+            types::jwk_thumbprint(&a).unwrap(),
+            types::jwk_thumbprint(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn account_store_register_returns_key_thumbprint_as_kid() {
+        let jwk = ed25519_jwk("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo");
+        let mut accounts = AccountStore::new();
+
+        let kid = accounts
+            .register(jwk.clone(), types::alg::ED25519.to_string())
+            .unwrap();
+
+        assert_eq!(kid, types::jwk_thumbprint(&jwk).unwrap());
+    }
+
+    #[test]
+    fn account_store_get_returns_none_for_unknown_kid() {
+        let accounts = AccountStore::new();
+
+        assert!(accounts.get("not-a-registered-kid").is_none());
+    }
+
+    #[test]
+    fn account_store_get_returns_registered_account() {
+        let jwk = ed25519_jwk("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo");
+        let mut accounts = AccountStore::new();
+        let kid = accounts
+            .register(jwk.clone(), types::alg::ED25519.to_string())
+            .unwrap();
+
+        let account = accounts.get(&kid).unwrap();
+
+        assert_eq!(account.alg, types::alg::ED25519);
+        assert_eq!(account.jwk.x, jwk.x);
+    }
 }
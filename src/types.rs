@@ -1,10 +1,296 @@
+// This file is textually included into both `holder.rs` and `verifier.rs`
+// via `mod types { include!("../types.rs"); }`, so each binary only calls
+// a subset of what's defined here (e.g. `holder` never calls
+// `verification_algorithm`, `verifier` never calls `b64url_encode` on its
+// own). Silence the resulting per-binary dead-code warnings here rather
+// than scattering `#[allow(dead_code)]` over individual items.
+#![allow(dead_code)]
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Payload {
+// Version/"typ" tag carried in every `Header` so old and new wire formats
+// stay distinguishable as the protocol evolves.
+pub const SIG_TYP_V1: &str = "spruce-sig-v1";
+
+// Algorithm identifiers carried in `Header::alg` (JWS-style names).
+pub mod alg {
+    pub const ED25519: &str = "EdDSA";
+    pub const ECDSA_P256: &str = "ES256";
+    pub const RSA_PKCS1_SHA256: &str = "RS256";
+}
+
+// A public key in JWK form. Which fields are populated depends on `kty`:
+// OKP (Ed25519) uses `crv`+`x`, EC (P-256) uses `crv`+`x`+`y`, and RSA uses
+// `n`+`e`. See RFC 7518 §6 / RFC 8037.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+}
+
+// The JWS protected header. Carries the signing algorithm, the server-issued
+// nonce, and the signer's public key, all of which end up inside the signed
+// region once it's base64url-encoded into `JoseJson::protected`. Exactly one
+// of `jwk` (the raw public key) or `kid` (a registered account identifier)
+// should be present, mirroring how ACME distinguishes new-account requests
+// from requests made on behalf of an existing account.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Header {
+    pub alg: String,
+    pub typ: String,
     pub nonce: String,
-    pub message: Vec<u8>,
-    pub signature: Vec<u8>,
-    pub public_key: Vec<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwk: Option<Jwk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+}
+
+// A JWS JSON Serialization-flavored envelope: `protected` and `payload` are
+// base64url(JSON)/base64url(bytes) respectively, and `signature` is computed
+// over `ASCII(base64url(protected) || "." || base64url(payload))`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JoseJson {
+    pub protected: String,
+    pub payload: String,
+    pub signature: String,
+}
+
+pub fn b64url_encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn b64url_decode(value: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|e| format!("Invalid base64url: {}", e))
+}
+
+// The exact bytes that get signed/verified for a `JoseJson` envelope. Per
+// JWS, this is built from the *transmitted* base64url strings, not a
+// re-encoding of the decoded values.
+pub fn signing_input(protected_b64: &str, payload_b64: &str) -> Vec<u8> {
+    format!("{}.{}", protected_b64, payload_b64).into_bytes()
+}
+
+// Infers the `alg` a JWK would be used with, from its `kty`/`crv`. Used when
+// registering an account, since the client submits only the public key.
+pub fn alg_for_jwk(jwk: &Jwk) -> Result<&'static str, String> {
+    match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+        ("OKP", Some("Ed25519")) => Ok(self::alg::ED25519),
+        ("EC", Some("P-256")) => Ok(self::alg::ECDSA_P256),
+        ("RSA", _) => Ok(self::alg::RSA_PKCS1_SHA256),
+        (kty, crv) => Err(format!("Unsupported key type/curve: {}/{:?}", kty, crv)),
+    }
+}
+
+// Computes the RFC 7638 JSON Web Key Thumbprint: the base64url-encoded
+// SHA-256 digest of the JWK's *required* members, serialized with sorted
+// keys and no whitespace. This is what `/register` hands back as the
+// account identifier, and what the server recomputes to confirm a `kid`
+// really does correspond to a given JWK.
+pub fn jwk_thumbprint(jwk: &Jwk) -> Result<String, String> {
+    let canonical = match jwk.kty.as_str() {
+        "OKP" => serde_json::json!({
+            "crv": jwk.crv.as_deref().ok_or("OKP JWK missing \"crv\"")?,
+            "kty": jwk.kty,
+            "x": jwk.x.as_deref().ok_or("OKP JWK missing \"x\"")?,
+        }),
+        "EC" => serde_json::json!({
+            "crv": jwk.crv.as_deref().ok_or("EC JWK missing \"crv\"")?,
+            "kty": jwk.kty,
+            "x": jwk.x.as_deref().ok_or("EC JWK missing \"x\"")?,
+            "y": jwk.y.as_deref().ok_or("EC JWK missing \"y\"")?,
+        }),
+        "RSA" => serde_json::json!({
+            "e": jwk.e.as_deref().ok_or("RSA JWK missing \"e\"")?,
+            "kty": jwk.kty,
+            "n": jwk.n.as_deref().ok_or("RSA JWK missing \"n\"")?,
+        }),
+        other => return Err(format!("Unsupported key type for thumbprint: {}", other)),
+    };
+
+    let canonical_json = serde_json::to_string(&canonical).map_err(|e| e.to_string())?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, canonical_json.as_bytes());
+    Ok(b64url_encode(digest.as_ref()))
+}
+
+// Maps a declared `alg` to the `ring` verification algorithm that checks it.
+// Returns `None` for anything we don't support, so callers can reject
+// unknown/mismatched algorithms up front.
+pub fn verification_algorithm(alg: &str) -> Option<&'static dyn ring::signature::VerificationAlgorithm> {
+    match alg {
+        self::alg::ED25519 => Some(&ring::signature::ED25519),
+        self::alg::ECDSA_P256 => Some(&ring::signature::ECDSA_P256_SHA256_FIXED),
+        self::alg::RSA_PKCS1_SHA256 => Some(&ring::signature::RSA_PKCS1_2048_8192_SHA256),
+        _ => None,
+    }
+}
+
+// Reconstructs the raw public key bytes `ring::signature::UnparsedPublicKey`
+// expects for `alg`, from the relevant JWK members.
+pub fn jwk_public_key_bytes(alg: &str, jwk: &Jwk) -> Result<Vec<u8>, String> {
+    match alg {
+        self::alg::ED25519 => {
+            let x = jwk.x.as_deref().ok_or("OKP JWK missing \"x\"")?;
+            b64url_decode(x)
+        }
+        self::alg::ECDSA_P256 => {
+            let x = b64url_decode(jwk.x.as_deref().ok_or("EC JWK missing \"x\"")?)?;
+            let y = b64url_decode(jwk.y.as_deref().ok_or("EC JWK missing \"y\"")?)?;
+            // Uncompressed SEC1 point: 0x04 || X || Y.
+            let mut point = Vec::with_capacity(1 + x.len() + y.len());
+            point.push(0x04);
+            point.extend_from_slice(&x);
+            point.extend_from_slice(&y);
+            Ok(point)
+        }
+        self::alg::RSA_PKCS1_SHA256 => {
+            let n = b64url_decode(jwk.n.as_deref().ok_or("RSA JWK missing \"n\"")?)?;
+            let e = b64url_decode(jwk.e.as_deref().ok_or("RSA JWK missing \"e\"")?)?;
+            Ok(der_encode_rsa_public_key(&n, &e))
+        }
+        other => Err(format!("Unsupported alg: {}", other)),
+    }
+}
+
+// DER-encodes a PKCS#1 `RSAPublicKey` (`SEQUENCE { modulus, publicExponent }`),
+// which is the form `ring`'s RSA verification expects. `ring` has no RSA key
+// generation support, so this is hand-rolled rather than pulled from a crate.
+fn der_encode_rsa_public_key(n: &[u8], e: &[u8]) -> Vec<u8> {
+    let mut sequence_content = der_integer(n);
+    sequence_content.extend_from_slice(&der_integer(e));
+    der_tlv(0x30, &sequence_content)
+}
+
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let mut value = bytes;
+    while value.len() > 1 && value[0] == 0 {
+        value = &value[1..];
+    }
+    let mut content = Vec::with_capacity(value.len() + 1);
+    if value.first().is_some_and(|b| b & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend_from_slice(value);
+    der_tlv(0x02, &content)
+}
+
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    out.extend_from_slice(&der_length(content.len()));
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_length(len: usize) -> Vec<u8> {
+    if len < 0x80 {
+        return vec![len as u8];
+    }
+    let mut bytes = len.to_be_bytes().to_vec();
+    while bytes.first() == Some(&0) {
+        bytes.remove(0);
+    }
+    let mut out = vec![0x80 | bytes.len() as u8];
+    out.extend_from_slice(&bytes);
+    out
+}
+
+// Parses a PKCS#1 `RSAPublicKey` DER blob (as produced by
+// `der_encode_rsa_public_key`, and by `ring`'s own `RsaKeyPair::public_key()`)
+// back into raw big-endian `(modulus, exponent)` bytes, for embedding as a
+// JWK's `n`/`e` members.
+pub fn der_decode_rsa_public_key(der: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut pos = 0;
+    let (tag, sequence) = der_read_tlv(der, &mut pos)?;
+    if tag != 0x30 {
+        return Err("Expected a SEQUENCE".to_string());
+    }
+
+    let mut inner_pos = 0;
+    let (n_tag, n) = der_read_tlv(&sequence, &mut inner_pos)?;
+    if n_tag != 0x02 {
+        return Err("Expected an INTEGER (modulus)".to_string());
+    }
+    let (e_tag, e) = der_read_tlv(&sequence, &mut inner_pos)?;
+    if e_tag != 0x02 {
+        return Err("Expected an INTEGER (exponent)".to_string());
+    }
+
+    Ok((strip_leading_zero(n), strip_leading_zero(e)))
+}
+
+fn der_read_tlv(der: &[u8], pos: &mut usize) -> Result<(u8, Vec<u8>), String> {
+    let tag = *der.get(*pos).ok_or("Truncated DER")?;
+    *pos += 1;
+
+    let first_len_byte = *der.get(*pos).ok_or("Truncated DER")?;
+    *pos += 1;
+    let len = if first_len_byte & 0x80 == 0 {
+        first_len_byte as usize
+    } else {
+        let num_length_bytes = (first_len_byte & 0x7f) as usize;
+        let mut len = 0usize;
+        for _ in 0..num_length_bytes {
+            let b = *der.get(*pos).ok_or("Truncated DER")?;
+            *pos += 1;
+            len = (len << 8) | b as usize;
+        }
+        len
+    };
+
+    let content = der
+        .get(*pos..*pos + len)
+        .ok_or("Truncated DER")?
+        .to_vec();
+    *pos += len;
+    Ok((tag, content))
 }
 
+fn strip_leading_zero(mut bytes: Vec<u8>) -> Vec<u8> {
+    while bytes.len() > 1 && bytes[0] == 0 {
+        bytes.remove(0);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rsa_public_key_der_round_trips_through_encode_and_decode() {
+        let n = vec![0xff; 256]; // leading bit set, exercises the 0-padding branch
+        let e = vec![0x01, 0x00, 0x01]; // 65537
+
+        let der = der_encode_rsa_public_key(&n, &e);
+        let (decoded_n, decoded_e) = der_decode_rsa_public_key(&der).unwrap();
+
+        assert_eq!(decoded_n, n);
+        assert_eq!(decoded_e, e);
+    }
+
+    #[test]
+    fn der_length_uses_multi_byte_form_past_127() {
+        // 1000 bytes of content needs a 2-byte length (0x82, 0x03, 0xe8).
+        let content = vec![0u8; 1000];
+        let tlv = der_tlv(0x02, &content);
+
+        assert_eq!(&tlv[..3], &[0x02, 0x82, 0x03]);
+        assert_eq!(tlv.len(), 1000 + 4);
+    }
+
+    #[test]
+    fn der_decode_rejects_truncated_input() {
+        assert!(der_decode_rsa_public_key(&[0x30, 0x05, 0x02, 0x01]).is_err());
+    }
+}